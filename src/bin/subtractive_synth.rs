@@ -22,6 +22,11 @@ fn qx49_controls(controller: u8, value: u8) -> Option<subsynth::Message> {
         25 => Some(subsynth::SetRelease(5.0*range)),
         26 => Some(subsynth::SetLFOFreq(10.0*range)),
         27 => Some(subsynth::SetVibrato(range)),
+        28 => Some(subsynth::SetDelayTime(2.0*range)),
+        29 => Some(subsynth::SetDelayMix(range)),
+        30 => Some(subsynth::SetReverbWet(range)),
+        31 => Some(subsynth::SetReverbRoomSize(range)),
+        32 => Some(subsynth::SetFilterLFO(4.0*range)),
         _ => None
     }
 }
@@ -46,9 +51,9 @@ fn main() {
             .osc1(Saw(PolyBlep)).osc2(Saw(PolyBlep))
             .control_map(qx49_controls)
     ).into(
-        Limiter::new(-3.0, 0.0, 1)
+        Limiter::new(-3.0, 0.0, 2)
     ).into(
-        audio_engine.default_output(1).unwrap()
+        audio_engine.default_output(2).unwrap()
     );
 
     println!("Playing. Press Enter to quit...");
@@ -11,10 +11,13 @@
 //!  1. Two oscillators, operating independently. The oscillators may be
 //!     transposed relative to each other.
 //!  2. Independent ADSR envelopes for each voice.
-//!  3. The signal is then passed through a multimode filter.
+//!  3. Each voice is passed through its own multimode filter, whose cutoff
+//!     can be swept by a dedicated filter envelope.
 //!
 //! Additionally, the synthesizer has an internal low frequency oscillator. This
 //! LFO may be used to add vibrato to the oscillators, or tremolo to the output.
+//! The output is finally passed through a delay and reverb effects section,
+//! both of which default to fully dry.
 //!
 //! # Controlling Tone
 //!
@@ -59,20 +62,28 @@
 //!         25 => Some(subsynth::SetRelease(5.0*range)),
 //!         26 => Some(subsynth::SetLFOFreq(10.0*range)),
 //!         27 => Some(subsynth::SetVibrato(range)),
+//!         28 => Some(subsynth::SetDelayTime(2.0*range)),
+//!         29 => Some(subsynth::SetDelayMix(range)),
+//!         30 => Some(subsynth::SetReverbWet(range)),
+//!         31 => Some(subsynth::SetReverbRoomSize(range)),
+//!         32 => Some(subsynth::SetFilterLFO(4.0*range)),
 //!         _ => None
 //!     }
 //! }
 //! ```
 
 extern crate oxcable;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 
 use oxcable::adsr::{self, Adsr};
 use oxcable::filters::{first_order, second_order};
 use oxcable::oscillator::{self, Oscillator, Waveform};
 use oxcable::tremolo::{self, Tremolo};
 use oxcable::types::{AudioDevice, MessageReceiver, MidiDevice, MidiEvent,
-        MidiMessage, Time, Sample};
-use oxcable::utils::helpers::{midi_note_to_freq, decibel_to_ratio};
+        MidiMessage, Time, Sample, SAMPLE_RATE};
+use oxcable::utils::helpers::{midi_note_to_freq, decibel_to_ratio, ratio_to_decibel};
 use oxcable::voice_array::VoiceArray;
 use oxcable::wrappers::Buffered;
 
@@ -108,29 +119,407 @@ pub enum Message {
     SetFilterFirstOrder(first_order::FilterMode),
     /// Sets the filter to a second order filter of the specified mode.
     SetFilterSecondOrder(second_order::FilterMode),
+    /// Sets the FM modulation index (depth) applied by osc2 onto osc1,
+    /// enabling FM mode when non-zero and disabling it when set back to 0.
+    SetFMDepth(f32),
+    /// Sets the modulator:carrier frequency ratio used when FM mode is enabled.
+    SetFMRatio(f32),
+    /// Sets how strongly note velocity affects output gain, from 0 (velocity
+    /// ignored) to 1 (full velocity range).
+    SetVelocitySensitivity(f32),
+    /// Sets how far note velocity pushes the filter cutoff open, in octaves
+    /// at full velocity; 0 disables velocity-to-cutoff tracking.
+    SetVelocityToCutoff(f32),
+    /// Sets how far the shared LFO sweeps the filter cutoff, in octaves; 0
+    /// disables filter LFO modulation.
+    SetFilterLFO(f32),
+    /// Sets the level of the noise oscillator mixed into each voice, from 0
+    /// (silent) to 1 (full level).
+    SetNoiseLevel(f32),
+    /// Sets the filter envelope's attack duration, in seconds.
+    SetFilterAttack(f32),
+    /// Sets the filter envelope's decay duration, in seconds.
+    SetFilterDecay(f32),
+    /// Sets the filter envelope's sustain level, from 0 to 1.
+    SetFilterSustain(f32),
+    /// Sets the filter envelope's release duration, in seconds.
+    SetFilterRelease(f32),
+    /// Sets how far the filter envelope sweeps the cutoff, in octaves.
+    SetFilterEnvDepth(f32),
+    /// Sets the pitch envelope's attack duration, in seconds.
+    SetPitchEnvAttack(f32),
+    /// Sets the pitch envelope's decay duration, in seconds.
+    SetPitchEnvDecay(f32),
+    /// Sets how far the pitch envelope sweeps the oscillators, in semitones.
+    /// Signed, so the envelope can sweep up or down.
+    SetPitchEnvDepth(f32),
+    /// Sets the delay effect's time, in seconds.
+    SetDelayTime(f32),
+    /// Sets the delay effect's feedback amount, from 0 to 1.
+    SetDelayFeedback(f32),
+    /// Sets the level of the delayed signal added on top of the dry signal,
+    /// from 0 (dry only) to 1 (delayed signal at full level); the dry signal
+    /// always passes at full level.
+    SetDelayMix(f32),
+    /// Sets the level of the reverb tail added on top of the dry signal,
+    /// from 0 (dry only) to 1 (tail at full level); the dry signal always
+    /// passes at full level.
+    SetReverbWet(f32),
+    /// Sets the reverb effect's room size, from 0 to 1.
+    SetReverbRoomSize(f32),
+    /// Sets the reverb effect's high frequency damping, from 0 to 1.
+    SetReverbDamping(f32),
+    /// Sets the oscillator mix balance, from 0 (osc1 only) to 1 (osc2 only).
+    SetOscMix(f32),
+    /// Switches the noise oscillator between white and pink noise.
+    SetPinkNoise(bool),
+    /// Switches the synth between polyphonic and monophonic (with legato)
+    /// modes.
+    SetMono(bool),
+    /// Sets the portamento/glide time used in monophonic mode, in seconds.
+    /// 0 disables glide, jumping directly to the new pitch.
+    SetGlide(f32),
+    /// Sets how many voices are stacked per note for the unison/"supersaw"
+    /// effect. 1 disables unison.
+    SetUnison(usize),
+    /// Sets how far unison voices are detuned from each other, in steps,
+    /// distributed symmetrically around the played pitch.
+    SetDetune(f32),
+    /// Sets how widely unison voices are spread across the stereo field,
+    /// from 0 (centered) to 1 (hard left/right at the extremes).
+    SetSpread(f32),
     /// Sends the provided MIDI event to the synth.
     SendMidiEvent(MidiEvent),
 }
 pub use self::Message::*;
 
 
-/// Internally used to track with filter type to use.
+/// The filter configuration captured by a [`SynthPatch`](struct.SynthPatch.html).
+///
+/// Stores the filter's shape and cutoff as plain data rather than oxcable's
+/// own `first_order`/`second_order` `FilterMode` types, since those aren't
+/// serde-enabled upstream.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PatchFilter {
+    FirstOrderLowPass(f32),
+    FirstOrderHighPass(f32),
+    SecondOrderLowPass(f32),
+    SecondOrderHighPass(f32),
+    SecondOrderBandPass(f32),
+}
+
+impl PatchFilter {
+    fn from_first_order(mode: first_order::FilterMode) -> Self {
+        use oxcable::filters::first_order::FilterMode::*;
+        match mode {
+            LowPass(cutoff) => PatchFilter::FirstOrderLowPass(cutoff),
+            HighPass(cutoff) => PatchFilter::FirstOrderHighPass(cutoff),
+        }
+    }
+
+    fn from_second_order(mode: second_order::FilterMode) -> Self {
+        use oxcable::filters::second_order::FilterMode::*;
+        match mode {
+            LowPass(cutoff) => PatchFilter::SecondOrderLowPass(cutoff),
+            HighPass(cutoff) => PatchFilter::SecondOrderHighPass(cutoff),
+            BandPass(cutoff) => PatchFilter::SecondOrderBandPass(cutoff),
+        }
+    }
+}
+
+/// A serializable mirror of [`Waveform`](../oxcable/oscillator/enum.Waveform.html),
+/// since oxcable's own type isn't serde-enabled. Covers the waveforms this
+/// crate's builder API is ever constructed with; anything else degrades to
+/// [`Sine`](#variant.Sine) rather than failing to save.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PatchWaveform {
+    Sine,
+    Saw,
+}
+
+fn waveform_to_patch(waveform: Waveform) -> PatchWaveform {
+    match waveform {
+        oscillator::Saw(_) => PatchWaveform::Saw,
+        _ => PatchWaveform::Sine,
+    }
+}
+
+fn patch_to_waveform(waveform: PatchWaveform) -> Waveform {
+    match waveform {
+        PatchWaveform::Sine => oscillator::Sine,
+        PatchWaveform::Saw => oscillator::Saw(oscillator::PolyBlep),
+    }
+}
+
+/// A serializable snapshot of every tone parameter the synth exposes.
+///
+/// A patch can be captured from a running synth with
+/// [`SubtractiveSynth::current_patch`](struct.SubtractiveSynth.html#method.current_patch),
+/// then saved (for example, as JSON) and later restored with
+/// [`SubtractiveSynth::apply_patch`](struct.SubtractiveSynth.html#method.apply_patch),
+/// letting tones be shipped as presets instead of rebuilt with the builder
+/// pattern.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SynthPatch {
+    pub gain: f32,
+    pub osc1: PatchWaveform,
+    pub osc2: PatchWaveform,
+    pub osc1_transpose: f32,
+    pub osc2_transpose: f32,
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+    pub lfo_freq: f32,
+    pub vibrato: f32,
+    pub tremolo: f32,
+    pub filter: PatchFilter,
+    pub fm_depth: f32,
+    pub fm_ratio: f32,
+    pub fm_enabled: bool,
+    pub velocity_sensitivity: f32,
+    pub velocity_to_cutoff: f32,
+    pub noise_level: f32,
+    pub pink_noise: bool,
+    pub filter_attack: f32,
+    pub filter_decay: f32,
+    pub filter_sustain: f32,
+    pub filter_release: f32,
+    pub filter_env_depth: f32,
+    pub filter_lfo: f32,
+    pub pitch_env_attack: f32,
+    pub pitch_env_decay: f32,
+    pub pitch_env_depth: f32,
+    pub osc_mix: f32,
+    pub delay_time: f32,
+    pub delay_feedback: f32,
+    pub delay_mix: f32,
+    pub reverb_wet: f32,
+    pub reverb_room_size: f32,
+    pub reverb_damping: f32,
+    pub mono: bool,
+    pub glide: f32,
+    pub unison: usize,
+    pub detune: f32,
+    pub spread: f32,
+}
+
+/// Internally used to track which filter type a voice uses.
 #[derive(Copy, Clone, Debug)]
 enum FilterType { FirstOrder, SecondOrder }
 
+/// The longest delay time the feedback delay line supports, in seconds.
+const MAX_DELAY_TIME: f32 = 2.0;
+
+/// A feedback delay line, used to implement the synth's delay effect.
+struct Delay {
+    buffer: Vec<Sample>,
+    pos: usize,
+    delay_samples: usize,
+    feedback: f32,
+    mix: f32,
+}
+
+impl Delay {
+    fn new() -> Self {
+        Delay {
+            buffer: vec![0.0; (MAX_DELAY_TIME*SAMPLE_RATE) as usize],
+            pos: 0,
+            delay_samples: 0,
+            feedback: 0.0,
+            mix: 0.0,
+        }
+    }
+
+    fn set_time(&mut self, time: f32) {
+        let max = self.buffer.len() - 1;
+        self.delay_samples = ((time*SAMPLE_RATE) as usize).min(max);
+    }
+
+    fn tick(&mut self, dry: Sample) -> Sample {
+        if self.delay_samples == 0 {
+            return dry;
+        }
+
+        let len = self.buffer.len();
+        let read_pos = (self.pos + len - self.delay_samples) % len;
+        let delayed = self.buffer[read_pos];
+        self.buffer[self.pos] = dry + self.feedback*delayed;
+        self.pos = (self.pos + 1) % len;
+        dry + self.mix*delayed
+    }
+}
+
+/// A Schroeder lowpass-comb filter, one of the 8 parallel filters that feed
+/// a [`Reverb`](struct.Reverb.html).
+struct CombFilter {
+    buffer: Vec<Sample>,
+    pos: usize,
+    filterstore: Sample,
+    feedback: f32,
+    damping: f32,
+}
+
+impl CombFilter {
+    fn new(delay_samples: usize) -> Self {
+        CombFilter {
+            buffer: vec![0.0; delay_samples],
+            pos: 0,
+            filterstore: 0.0,
+            feedback: 0.0,
+            damping: 0.0,
+        }
+    }
+
+    fn tick(&mut self, input: Sample) -> Sample {
+        let output = self.buffer[self.pos];
+        self.filterstore = output*(1.0-self.damping) + self.filterstore*self.damping;
+        self.buffer[self.pos] = input + self.feedback*self.filterstore;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        output
+    }
+}
+
+/// An allpass diffuser, one of the 4 filters run in series after the
+/// [`Reverb`](struct.Reverb.html)'s comb filters.
+struct AllpassFilter {
+    buffer: Vec<Sample>,
+    pos: usize,
+    feedback: f32,
+}
+
+impl AllpassFilter {
+    fn new(delay_samples: usize, feedback: f32) -> Self {
+        AllpassFilter { buffer: vec![0.0; delay_samples], pos: 0, feedback: feedback }
+    }
+
+    fn tick(&mut self, input: Sample) -> Sample {
+        let buffered = self.buffer[self.pos];
+        let output = buffered - input;
+        self.buffer[self.pos] = input + self.feedback*buffered;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        output
+    }
+}
+
+/// The comb filter delay lengths used by [`Reverb`](struct.Reverb.html),
+/// in samples at 44.1kHz (Freeverb's standard tunings).
+const COMB_TUNINGS: [usize; 8] = [1557, 1617, 1491, 1422, 1277, 1356, 1188, 1116];
+
+/// The allpass filter delay lengths used by [`Reverb`](struct.Reverb.html),
+/// in samples at 44.1kHz.
+const ALLPASS_TUNINGS: [usize; 4] = [225, 556, 441, 341];
+
+/// A Freeverb-style reverb: 8 parallel Schroeder lowpass-comb filters summed
+/// and then diffused through 4 series allpass filters.
+struct Reverb {
+    combs: Vec<CombFilter>,
+    allpasses: Vec<AllpassFilter>,
+    wet: f32,
+}
+
+impl Reverb {
+    fn new() -> Self {
+        let scale = SAMPLE_RATE / 44100.0;
+        let combs = COMB_TUNINGS.iter()
+            .map(|&tuning| CombFilter::new((tuning as f32*scale) as usize))
+            .collect();
+        let allpasses = ALLPASS_TUNINGS.iter()
+            .map(|&tuning| AllpassFilter::new((tuning as f32*scale) as usize, 0.5))
+            .collect();
+        let mut reverb = Reverb { combs: combs, allpasses: allpasses, wet: 0.0 };
+        reverb.set_room_size(0.5);
+        reverb.set_damping(0.5);
+        reverb
+    }
+
+    fn set_room_size(&mut self, room_size: f32) {
+        let feedback = 0.28*room_size + 0.7;
+        for comb in self.combs.iter_mut() {
+            comb.feedback = feedback;
+        }
+    }
+
+    fn set_damping(&mut self, damping: f32) {
+        for comb in self.combs.iter_mut() {
+            comb.damping = damping;
+        }
+    }
+
+    fn tick(&mut self, dry: Sample) -> Sample {
+        let mut wet = self.combs.iter_mut().fold(0.0, |sum, comb| sum + comb.tick(dry));
+        for allpass in self.allpasses.iter_mut() {
+            wet = allpass.tick(wet);
+        }
+        dry + self.wet*wet
+    }
+}
+
+/// Extracts the cutoff frequency carried by a first order filter mode.
+fn first_order_cutoff(mode: first_order::FilterMode) -> f32 {
+    use oxcable::filters::first_order::FilterMode::*;
+    match mode {
+        LowPass(cutoff) => cutoff,
+        HighPass(cutoff) => cutoff,
+    }
+}
+
+/// Rebuilds a first order filter mode around a new cutoff frequency, keeping
+/// the same low/high-pass shape.
+fn first_order_with_cutoff(mode: first_order::FilterMode, cutoff: f32) -> first_order::FilterMode {
+    use oxcable::filters::first_order::FilterMode::*;
+    match mode {
+        LowPass(_) => LowPass(cutoff),
+        HighPass(_) => HighPass(cutoff),
+    }
+}
+
+/// Extracts the cutoff frequency carried by a second order filter mode.
+fn second_order_cutoff(mode: second_order::FilterMode) -> f32 {
+    use oxcable::filters::second_order::FilterMode::*;
+    match mode {
+        LowPass(cutoff) => cutoff,
+        HighPass(cutoff) => cutoff,
+        BandPass(cutoff) => cutoff,
+    }
+}
+
+/// Rebuilds a second order filter mode around a new cutoff frequency, keeping
+/// the same low/high/band-pass shape.
+fn second_order_with_cutoff(mode: second_order::FilterMode, cutoff: f32) -> second_order::FilterMode {
+    use oxcable::filters::second_order::FilterMode::*;
+    match mode {
+        LowPass(_) => LowPass(cutoff),
+        HighPass(_) => HighPass(cutoff),
+        BandPass(_) => BandPass(cutoff),
+    }
+}
+
 /// A polyphonic subtractive synthesizer.
 pub struct SubtractiveSynth<M: MidiDevice> {
     voices: VoiceArray<SubtractiveSynthVoice>,
     controls: Option<Box<Fn(u8, u8) -> Option<Message>>>,
     midi: M,
     gain: f32,
+    patch: SynthPatch,
+    mono: bool,
+    held_notes: Vec<u8>,
+    num_voices: usize,
+    unison: usize,
+    detune: f32,
+    spread: f32,
+    pan: Vec<f32>,
+    active: Vec<Option<u8>>,
+    note_voices: Vec<(u8, Vec<usize>)>,
 
     // audio devices
     lfo: Buffered<Oscillator>,
-    filter: FilterType,
-    first_filter: Buffered<first_order::Filter>,
-    second_filter: Buffered<second_order::Filter>,
-    tremolo: Buffered<Tremolo>,
+    tremolo_l: Buffered<Tremolo>,
+    tremolo_r: Buffered<Tremolo>,
+    delay_l: Delay,
+    delay_r: Delay,
+    reverb_l: Reverb,
+    reverb_r: Reverb,
 }
 
 impl<M> SubtractiveSynth<M> where M: MidiDevice {
@@ -140,8 +529,9 @@ impl<M> SubtractiveSynth<M> where M: MidiDevice {
     /// * `num_voices`: the maximum voices that can play at one time.
     pub fn new(midi: M, num_voices: usize) -> Self {
         let mut voices = Vec::with_capacity(num_voices);
-        for _i in (0 .. num_voices) {
-            voices.push(SubtractiveSynthVoice::new());
+        for i in (0 .. num_voices) {
+            let seed = 0x9E3779B9u32 ^ (i as u32).wrapping_mul(0x85EBCA6B).wrapping_add(1);
+            voices.push(SubtractiveSynthVoice::new(seed));
         }
         let voice_array = VoiceArray::new(voices);
 
@@ -150,13 +540,65 @@ impl<M> SubtractiveSynth<M> where M: MidiDevice {
             controls: None,
             midi: midi,
             gain: 1.0/num_voices as f32,
+            patch: SynthPatch {
+                gain: ratio_to_decibel(1.0/num_voices as f32),
+                osc1: PatchWaveform::Sine,
+                osc2: PatchWaveform::Sine,
+                osc1_transpose: 0.0,
+                osc2_transpose: 0.0,
+                attack: 0.0,
+                decay: 0.0,
+                sustain: 1.0,
+                release: 0.0,
+                lfo_freq: 10.0,
+                vibrato: 0.0,
+                tremolo: 0.0,
+                filter: PatchFilter::FirstOrderLowPass(20000.0),
+                fm_depth: 0.0,
+                fm_ratio: 1.0,
+                fm_enabled: false,
+                velocity_sensitivity: 0.0,
+                velocity_to_cutoff: 0.0,
+                noise_level: 0.0,
+                pink_noise: false,
+                filter_attack: 0.0,
+                filter_decay: 0.0,
+                filter_sustain: 1.0,
+                filter_release: 0.0,
+                filter_env_depth: 0.0,
+                filter_lfo: 0.0,
+                pitch_env_attack: 0.0,
+                pitch_env_decay: 0.0,
+                pitch_env_depth: 0.0,
+                osc_mix: 0.5,
+                delay_time: 0.0,
+                delay_feedback: 0.0,
+                delay_mix: 0.0,
+                reverb_wet: 0.0,
+                reverb_room_size: 0.5,
+                reverb_damping: 0.5,
+                mono: false,
+                glide: 0.0,
+                unison: 1,
+                detune: 0.0,
+                spread: 0.0,
+            },
+            mono: false,
+            held_notes: Vec::new(),
+            num_voices: num_voices,
+            unison: 1,
+            detune: 0.0,
+            spread: 0.0,
+            pan: vec![0.0; num_voices],
+            active: vec![None; num_voices],
+            note_voices: Vec::new(),
             lfo: Buffered::from(Oscillator::new(oscillator::Sine).freq(10.0)),
-            filter: FilterType::FirstOrder,
-            first_filter: Buffered::from(first_order::Filter::new(
-                first_order::LowPass(20000.0), 1)),
-            second_filter: Buffered::from(second_order::Filter::new(
-                second_order::LowPass(20000.0), 1)),
-            tremolo: Buffered::from(Tremolo::new(0.0)),
+            tremolo_l: Buffered::from(Tremolo::new(0.0)),
+            tremolo_r: Buffered::from(Tremolo::new(0.0)),
+            delay_l: Delay::new(),
+            delay_r: Delay::new(),
+            reverb_l: Reverb::new(),
+            reverb_r: Reverb::new(),
         }
     }
 
@@ -252,14 +694,270 @@ impl<M> SubtractiveSynth<M> where M: MidiDevice {
         self
     }
 
+    /// Enables two-operator FM between the oscillators, then return the same
+    /// synth.
+    ///
+    /// * `depth` specifies the modulation index `I`; a non-zero depth is what
+    ///   enables FM mode (see [`SetFMDepth`](enum.Message.html#variant.SetFMDepth)),
+    ///   so passing 0 here leaves FM disabled.
+    /// * `ratio` specifies the modulator:carrier frequency ratio.
+    ///
+    /// While enabled, `osc2` acts as a phase modulator for `osc1` instead of
+    /// sounding on its own; the parallel-mix behavior remains the default when
+    /// this is not called.
+    pub fn fm(mut self, depth: f32, ratio: f32) -> Self {
+        self.handle_message(SetFMDepth(depth));
+        self.handle_message(SetFMRatio(ratio));
+        self
+    }
+
+    /// Sets how strongly note velocity affects output gain, then return the
+    /// same synth.
+    ///
+    /// `sensitivity` ranges from 0 (velocity is ignored, every note plays at
+    /// full gain) to 1 (gain tracks velocity across its full range).
+    pub fn velocity_sensitivity(mut self, sensitivity: f32) -> Self {
+        self.handle_message(SetVelocitySensitivity(sensitivity));
+        self
+    }
+
+    /// Sets how far note velocity pushes the filter cutoff open, in octaves
+    /// at full velocity, then return the same synth.
+    pub fn velocity_to_cutoff(mut self, octaves: f32) -> Self {
+        self.handle_message(SetVelocityToCutoff(octaves));
+        self
+    }
+
+    /// Sets how far the shared LFO sweeps the filter cutoff, in octaves,
+    /// then return the same synth. Useful for filter-sweep and wobble-bass
+    /// tones. The sweep rate is the LFO's frequency, set with
+    /// [`lfo`](#method.lfo) (10 Hz by default).
+    pub fn filter_lfo(mut self, octaves: f32) -> Self {
+        self.handle_message(SetFilterLFO(octaves));
+        self
+    }
+
+    /// Sets the level of the noise oscillator mixed into each voice, then
+    /// return the same synth.
+    pub fn noise(mut self, level: f32) -> Self {
+        self.handle_message(SetNoiseLevel(level));
+        self
+    }
+
+    /// Sets the synth's per-voice filter envelope, then return the same
+    /// synth.
+    ///
+    /// * `attack_time` specifies the length of the attack in seconds.
+    /// * `decay_time` specifies the length of the decay in seconds.
+    /// * `sustain_level` specifies the amplitude of the sustain from 0 to 1.
+    /// * `release_time` specifies the length of the release in seconds.
+    /// * `depth` specifies how far the envelope sweeps the cutoff, in
+    ///   octaves.
+    pub fn filter_envelope(mut self, attack_time: f32, decay_time: f32,
+               sustain_level: f32, release_time: f32, depth: f32) -> Self {
+        self.handle_message(SetFilterAttack(attack_time));
+        self.handle_message(SetFilterDecay(decay_time));
+        self.handle_message(SetFilterSustain(sustain_level));
+        self.handle_message(SetFilterRelease(release_time));
+        self.handle_message(SetFilterEnvDepth(depth));
+        self
+    }
+
+    /// Sets the synth's delay effect, then return the same synth.
+    ///
+    /// * `time` specifies the delay time, in seconds.
+    /// * `feedback` specifies how much of the delayed signal feeds back into
+    ///   itself, from 0 to 1.
+    /// * `mix` specifies the level of the delayed signal added on top of the
+    ///   dry signal, from 0 (dry only) to 1 (delayed signal at full level).
+    pub fn delay(mut self, time: f32, feedback: f32, mix: f32) -> Self {
+        self.handle_message(SetDelayTime(time));
+        self.handle_message(SetDelayFeedback(feedback));
+        self.handle_message(SetDelayMix(mix));
+        self
+    }
+
+    /// Sets the synth's Freeverb-style reverb effect, then return the same
+    /// synth.
+    ///
+    /// * `wet` specifies the level of the reverb tail added on top of the dry
+    ///   signal, from 0 (dry only) to 1 (tail at full level).
+    /// * `room_size` specifies the simulated room size, from 0 to 1.
+    /// * `damping` specifies how much high frequency content decays faster
+    ///   than the rest of the tail, from 0 to 1.
+    pub fn reverb(mut self, wet: f32, room_size: f32, damping: f32) -> Self {
+        self.handle_message(SetReverbWet(wet));
+        self.handle_message(SetReverbRoomSize(room_size));
+        self.handle_message(SetReverbDamping(damping));
+        self
+    }
+
+    /// Sets the synth's per-voice pitch envelope, then return the same synth.
+    ///
+    /// * `attack_time` specifies the length of the attack in seconds.
+    /// * `decay_time` specifies the length of the decay in seconds.
+    /// * `depth` specifies how far the envelope sweeps the oscillators, in
+    ///   semitones. Signed, so the envelope can sweep up or down before
+    ///   settling back on the played note as it decays.
+    pub fn pitch_envelope(mut self, attack_time: f32, decay_time: f32,
+               depth: f32) -> Self {
+        self.handle_message(SetPitchEnvAttack(attack_time));
+        self.handle_message(SetPitchEnvDecay(decay_time));
+        self.handle_message(SetPitchEnvDepth(depth));
+        self
+    }
+
+    /// Sets the crossfade balance between the two oscillators, then return
+    /// the same synth.
+    ///
+    /// `mix` ranges from 0 (osc1 only) to 1 (osc2 only); has no effect while
+    /// FM mode is enabled, since only the carrier reaches the envelope then.
+    pub fn osc_mix(mut self, mix: f32) -> Self {
+        self.handle_message(SetOscMix(mix));
+        self
+    }
+
+    /// Switches the noise oscillator between white and pink noise, then
+    /// return the same synth.
+    pub fn pink_noise(mut self, pink: bool) -> Self {
+        self.handle_message(SetPinkNoise(pink));
+        self
+    }
+
+    /// Switches the synth between polyphonic and monophonic (legato) modes,
+    /// then return the same synth.
+    ///
+    /// In mono mode, every incoming note is routed to a single voice instead
+    /// of being allocated across the voice array; holding a note and playing
+    /// another plays legato (the envelopes keep running) and glides the
+    /// pitch instead of jumping, per [`glide`](#method.glide). Releasing a
+    /// note falls back to the most recently held note still down, if any.
+    pub fn mono(mut self, mono: bool) -> Self {
+        self.handle_message(SetMono(mono));
+        self
+    }
+
+    /// Sets the portamento/glide time used in mono mode, in seconds, then
+    /// return the same synth.
+    pub fn glide(mut self, time: f32) -> Self {
+        self.handle_message(SetGlide(time));
+        self
+    }
+
+    /// Sets how many voices are stacked per note for a "supersaw"-style
+    /// unison effect, then return the same synth.
+    pub fn unison(mut self, voices: usize) -> Self {
+        self.handle_message(SetUnison(voices));
+        self
+    }
+
+    /// Sets how far unison voices are detuned from each other in steps, then
+    /// return the same synth.
+    pub fn detune(mut self, steps: f32) -> Self {
+        self.handle_message(SetDetune(steps));
+        self
+    }
+
+    /// Sets how widely unison voices are spread across the stereo field,
+    /// then return the same synth.
+    pub fn spread(mut self, spread: f32) -> Self {
+        self.handle_message(SetSpread(spread));
+        self
+    }
+
+    /// Returns a snapshot of the synth's current tone as a [`SynthPatch`](
+    /// struct.SynthPatch.html), suitable for saving as a preset.
+    pub fn current_patch(&self) -> SynthPatch {
+        self.patch
+    }
+
+    /// Restores the synth's tone from a previously captured [`SynthPatch`](
+    /// struct.SynthPatch.html).
+    pub fn apply_patch(&mut self, patch: &SynthPatch) {
+        self.handle_message(SetGain(patch.gain));
+        self.handle_message(SetOsc1(patch_to_waveform(patch.osc1)));
+        self.handle_message(SetOsc2(patch_to_waveform(patch.osc2)));
+        self.handle_message(SetOsc1Transpose(patch.osc1_transpose));
+        self.handle_message(SetOsc2Transpose(patch.osc2_transpose));
+        self.handle_message(SetAttack(patch.attack));
+        self.handle_message(SetDecay(patch.decay));
+        self.handle_message(SetSustain(patch.sustain));
+        self.handle_message(SetRelease(patch.release));
+        self.handle_message(SetLFOFreq(patch.lfo_freq));
+        self.handle_message(SetVibrato(patch.vibrato));
+        self.handle_message(SetTremolo(patch.tremolo));
+        match patch.filter {
+            PatchFilter::FirstOrderLowPass(cutoff) =>
+                self.handle_message(SetFilterFirstOrder(first_order::LowPass(cutoff))),
+            PatchFilter::FirstOrderHighPass(cutoff) =>
+                self.handle_message(SetFilterFirstOrder(first_order::HighPass(cutoff))),
+            PatchFilter::SecondOrderLowPass(cutoff) =>
+                self.handle_message(SetFilterSecondOrder(second_order::LowPass(cutoff))),
+            PatchFilter::SecondOrderHighPass(cutoff) =>
+                self.handle_message(SetFilterSecondOrder(second_order::HighPass(cutoff))),
+            PatchFilter::SecondOrderBandPass(cutoff) =>
+                self.handle_message(SetFilterSecondOrder(second_order::BandPass(cutoff))),
+        }
+        self.handle_message(SetFMDepth(patch.fm_depth));
+        self.handle_message(SetFMRatio(patch.fm_ratio));
+        self.handle_message(SetVelocitySensitivity(patch.velocity_sensitivity));
+        self.handle_message(SetVelocityToCutoff(patch.velocity_to_cutoff));
+        self.handle_message(SetNoiseLevel(patch.noise_level));
+        self.handle_message(SetPinkNoise(patch.pink_noise));
+        self.handle_message(SetFilterAttack(patch.filter_attack));
+        self.handle_message(SetFilterDecay(patch.filter_decay));
+        self.handle_message(SetFilterSustain(patch.filter_sustain));
+        self.handle_message(SetFilterRelease(patch.filter_release));
+        self.handle_message(SetFilterEnvDepth(patch.filter_env_depth));
+        self.handle_message(SetFilterLFO(patch.filter_lfo));
+        self.handle_message(SetPitchEnvAttack(patch.pitch_env_attack));
+        self.handle_message(SetPitchEnvDecay(patch.pitch_env_decay));
+        self.handle_message(SetPitchEnvDepth(patch.pitch_env_depth));
+        self.handle_message(SetOscMix(patch.osc_mix));
+        self.handle_message(SetDelayTime(patch.delay_time));
+        self.handle_message(SetDelayFeedback(patch.delay_feedback));
+        self.handle_message(SetDelayMix(patch.delay_mix));
+        self.handle_message(SetReverbWet(patch.reverb_wet));
+        self.handle_message(SetReverbRoomSize(patch.reverb_room_size));
+        self.handle_message(SetReverbDamping(patch.reverb_damping));
+        self.handle_message(SetMono(patch.mono));
+        self.handle_message(SetGlide(patch.glide));
+        self.handle_message(SetUnison(patch.unison));
+        self.handle_message(SetDetune(patch.detune));
+        self.handle_message(SetSpread(patch.spread));
+    }
+
     // Handles MIDI events.
     fn handle_event(&mut self, event: MidiEvent) {
         match event.payload {
-            MidiMessage::NoteOn(note, _) => {
-                self.voices.note_on(note).handle_event(event);
+            MidiMessage::NoteOn(note, velocity) => {
+                if self.mono {
+                    self.held_notes.retain(|&n| n != note);
+                    let legato = !self.held_notes.is_empty();
+                    self.held_notes.push(note);
+                    if let Some(voice) = self.voices.iter_mut().next() {
+                        voice.note_on(midi_note_to_freq(note), Some(velocity), legato);
+                    }
+                } else if self.unison > 1 {
+                    self.unison_note_on(note, velocity);
+                } else {
+                    self.voices.note_on(note).handle_event(event);
+                }
             },
             MidiMessage::NoteOff(note, _) => {
-                self.voices.note_off(note).map_or((), |d| d.handle_event(event));
+                if self.mono {
+                    self.held_notes.retain(|&n| n != note);
+                    if let Some(voice) = self.voices.iter_mut().next() {
+                        match self.held_notes.last() {
+                            Some(&prev) => voice.note_on(midi_note_to_freq(prev), None, true),
+                            None => voice.handle_event(event),
+                        }
+                    }
+                } else if self.unison > 1 {
+                    self.unison_note_off(note, event);
+                } else {
+                    self.voices.note_off(note).map_or((), |d| d.handle_event(event));
+                }
             },
             MidiMessage::ControlChange(controller, value) => {
                 let msg = match self.controls {
@@ -275,6 +973,66 @@ impl<M> SubtractiveSynth<M> where M: MidiDevice {
             }
         }
     }
+
+    // Allocates `self.unison` free voices for `note`, distributing their
+    // detune and stereo pan symmetrically around the played pitch, and
+    // records the allocation so a later note off can release all of them.
+    fn unison_note_on(&mut self, note: u8, velocity: u8) {
+        let n = self.unison;
+        let freq = midi_note_to_freq(note);
+        let mut slots = Vec::with_capacity(n);
+        for _ in 0 .. n {
+            match self.active.iter().position(|slot| slot.is_none()) {
+                Some(slot) => {
+                    self.active[slot] = Some(note);
+                    slots.push(slot);
+                },
+                None => break,
+            }
+        }
+
+        for (k, &slot) in slots.iter().enumerate() {
+            let spread = if n > 1 { (k as f32/(n-1) as f32 - 0.5) * 2.0 } else { 0.0 };
+            let detune = if n > 1 { (k as f32/(n-1) as f32 - 0.5) * self.detune } else { 0.0 };
+            self.pan[slot] = spread * self.spread;
+
+            if let Some(voice) = self.voices.iter_mut().nth(slot) {
+                voice.osc1.handle_message(
+                    oscillator::SetTranspose(self.patch.osc1_transpose + detune));
+                voice.osc2.handle_message(
+                    oscillator::SetTranspose(self.patch.osc2_transpose + detune));
+                voice.note_on(freq, Some(velocity), false);
+            }
+        }
+        self.note_voices.push((note, slots));
+    }
+
+    // Releases every voice allocated to `note` by `unison_note_on`.
+    fn unison_note_off(&mut self, note: u8, event: MidiEvent) {
+        let found = self.note_voices.iter().position(|&(n, _)| n == note);
+        if let Some(idx) = found {
+            let (_, slots) = self.note_voices.remove(idx);
+            for slot in slots {
+                self.active[slot] = None;
+                self.pan[slot] = 0.0;
+                if let Some(voice) = self.voices.iter_mut().nth(slot) {
+                    voice.handle_event(event);
+                    voice.osc1.handle_message(
+                        oscillator::SetTranspose(self.patch.osc1_transpose));
+                    voice.osc2.handle_message(
+                        oscillator::SetTranspose(self.patch.osc2_transpose));
+                }
+            }
+        }
+    }
+}
+
+/// Computes the left/right gain for a pan position from -1 (hard left) to 1
+/// (hard right); 0 (centered) passes the signal at full level to both
+/// channels.
+fn pan_gains(pan: f32) -> (f32, f32) {
+    let pan = pan.max(-1.0).min(1.0);
+    (1.0 - pan.max(0.0), 1.0 + pan.min(0.0))
 }
 
 impl<M> MessageReceiver for SubtractiveSynth<M> where M: MidiDevice {
@@ -283,49 +1041,59 @@ impl<M> MessageReceiver for SubtractiveSynth<M> where M: MidiDevice {
         match msg {
             SetGain(gain) => {
                 self.gain = decibel_to_ratio(gain);
+                self.patch.gain = gain;
             },
             SetOsc1(waveform) => {
                 for voice in self.voices.iter_mut() {
                     voice.osc1.handle_message(oscillator::SetWaveform(waveform));
                 }
+                self.patch.osc1 = waveform_to_patch(waveform);
             },
             SetOsc2(waveform) => {
                 for voice in self.voices.iter_mut() {
                     voice.osc2.handle_message(oscillator::SetWaveform(waveform));
                 }
+                self.patch.osc2 = waveform_to_patch(waveform);
             },
             SetOsc1Transpose(steps) => {
                 for voice in self.voices.iter_mut() {
                     voice.osc1.handle_message(oscillator::SetTranspose(steps));
                 }
+                self.patch.osc1_transpose = steps;
             },
             SetOsc2Transpose(steps) => {
                 for voice in self.voices.iter_mut() {
                     voice.osc2.handle_message(oscillator::SetTranspose(steps));
                 }
+                self.patch.osc2_transpose = steps;
             },
             SetAttack(attack) => {
                 for voice in self.voices.iter_mut() {
                     voice.adsr.handle_message(adsr::SetAttack(attack));
                 }
+                self.patch.attack = attack;
             },
             SetDecay(decay) => {
                 for voice in self.voices.iter_mut() {
                     voice.adsr.handle_message(adsr::SetDecay(decay));
                 }
+                self.patch.decay = decay;
             },
             SetSustain(sustain) => {
                 for voice in self.voices.iter_mut() {
                     voice.adsr.handle_message(adsr::SetSustain(sustain));
                 }
+                self.patch.sustain = sustain;
             },
             SetRelease(release) => {
                 for voice in self.voices.iter_mut() {
                     voice.adsr.handle_message(adsr::SetRelease(release));
                 }
+                self.patch.release = release;
             },
             SetLFOFreq(freq) => {
                 self.lfo.handle_message(oscillator::SetFreq(freq));
+                self.patch.lfo_freq = freq;
             },
             SetVibrato(intensity) => {
                 for voice in self.voices.iter_mut() {
@@ -334,17 +1102,179 @@ impl<M> MessageReceiver for SubtractiveSynth<M> where M: MidiDevice {
                     voice.osc2.handle_message(
                         oscillator::SetLFOIntensity(intensity));
                 }
+                self.patch.vibrato = intensity;
             },
             SetTremolo(intensity) => {
-                self.tremolo.handle_message(tremolo::SetIntensity(intensity));
+                self.tremolo_l.handle_message(tremolo::SetIntensity(intensity));
+                self.tremolo_r.handle_message(tremolo::SetIntensity(intensity));
+                self.patch.tremolo = intensity;
             },
             SetFilterFirstOrder(mode) => {
-                self.filter = FilterType::FirstOrder;
-                self.first_filter.handle_message(first_order::SetMode(mode));
+                for voice in self.voices.iter_mut() {
+                    voice.filter = FilterType::FirstOrder;
+                    voice.base_cutoff = first_order_cutoff(mode);
+                    voice.first_mode = mode;
+                }
+                self.patch.filter = PatchFilter::from_first_order(mode);
             },
             SetFilterSecondOrder(mode) => {
-                self.filter = FilterType::SecondOrder;
-                self.second_filter.handle_message(second_order::SetMode(mode));
+                for voice in self.voices.iter_mut() {
+                    voice.filter = FilterType::SecondOrder;
+                    voice.base_cutoff = second_order_cutoff(mode);
+                    voice.second_mode = mode;
+                }
+                self.patch.filter = PatchFilter::from_second_order(mode);
+            },
+            SetFMDepth(depth) => {
+                for voice in self.voices.iter_mut() {
+                    voice.fm_depth = depth;
+                    voice.fm_enabled = depth != 0.0;
+                }
+                self.patch.fm_depth = depth;
+                self.patch.fm_enabled = depth != 0.0;
+            },
+            SetFMRatio(ratio) => {
+                for voice in self.voices.iter_mut() {
+                    voice.fm_ratio = ratio;
+                }
+                self.patch.fm_ratio = ratio;
+            },
+            SetVelocitySensitivity(sensitivity) => {
+                for voice in self.voices.iter_mut() {
+                    voice.velocity_sensitivity = sensitivity;
+                }
+                self.patch.velocity_sensitivity = sensitivity;
+            },
+            SetVelocityToCutoff(octaves) => {
+                for voice in self.voices.iter_mut() {
+                    voice.velocity_to_cutoff = octaves;
+                }
+                self.patch.velocity_to_cutoff = octaves;
+            },
+            SetFilterLFO(octaves) => {
+                for voice in self.voices.iter_mut() {
+                    voice.filter_lfo_depth = octaves;
+                }
+                self.patch.filter_lfo = octaves;
+            },
+            SetNoiseLevel(level) => {
+                for voice in self.voices.iter_mut() {
+                    voice.noise_level = level;
+                }
+                self.patch.noise_level = level;
+            },
+            SetFilterAttack(attack) => {
+                for voice in self.voices.iter_mut() {
+                    voice.filter_adsr.handle_message(adsr::SetAttack(attack));
+                }
+                self.patch.filter_attack = attack;
+            },
+            SetFilterDecay(decay) => {
+                for voice in self.voices.iter_mut() {
+                    voice.filter_adsr.handle_message(adsr::SetDecay(decay));
+                }
+                self.patch.filter_decay = decay;
+            },
+            SetFilterSustain(sustain) => {
+                for voice in self.voices.iter_mut() {
+                    voice.filter_adsr.handle_message(adsr::SetSustain(sustain));
+                }
+                self.patch.filter_sustain = sustain;
+            },
+            SetFilterRelease(release) => {
+                for voice in self.voices.iter_mut() {
+                    voice.filter_adsr.handle_message(adsr::SetRelease(release));
+                }
+                self.patch.filter_release = release;
+            },
+            SetFilterEnvDepth(depth) => {
+                for voice in self.voices.iter_mut() {
+                    voice.filter_env_depth = depth;
+                }
+                self.patch.filter_env_depth = depth;
+            },
+            SetDelayTime(time) => {
+                self.delay_l.set_time(time);
+                self.delay_r.set_time(time);
+                self.patch.delay_time = time;
+            },
+            SetDelayFeedback(feedback) => {
+                self.delay_l.feedback = feedback;
+                self.delay_r.feedback = feedback;
+                self.patch.delay_feedback = feedback;
+            },
+            SetDelayMix(mix) => {
+                self.delay_l.mix = mix;
+                self.delay_r.mix = mix;
+                self.patch.delay_mix = mix;
+            },
+            SetReverbWet(wet) => {
+                self.reverb_l.wet = wet;
+                self.reverb_r.wet = wet;
+                self.patch.reverb_wet = wet;
+            },
+            SetReverbRoomSize(room_size) => {
+                self.reverb_l.set_room_size(room_size);
+                self.reverb_r.set_room_size(room_size);
+                self.patch.reverb_room_size = room_size;
+            },
+            SetReverbDamping(damping) => {
+                self.reverb_l.set_damping(damping);
+                self.reverb_r.set_damping(damping);
+                self.patch.reverb_damping = damping;
+            },
+            SetOscMix(mix) => {
+                for voice in self.voices.iter_mut() {
+                    voice.osc_mix = mix;
+                }
+                self.patch.osc_mix = mix;
+            },
+            SetPinkNoise(pink) => {
+                for voice in self.voices.iter_mut() {
+                    voice.pink_noise = pink;
+                }
+                self.patch.pink_noise = pink;
+            },
+            SetPitchEnvAttack(attack) => {
+                for voice in self.voices.iter_mut() {
+                    voice.pitch_adsr.handle_message(adsr::SetAttack(attack));
+                }
+                self.patch.pitch_env_attack = attack;
+            },
+            SetPitchEnvDecay(decay) => {
+                for voice in self.voices.iter_mut() {
+                    voice.pitch_adsr.handle_message(adsr::SetDecay(decay));
+                }
+                self.patch.pitch_env_decay = decay;
+            },
+            SetPitchEnvDepth(depth) => {
+                for voice in self.voices.iter_mut() {
+                    voice.pitch_env_depth = depth;
+                }
+                self.patch.pitch_env_depth = depth;
+            },
+            SetMono(mono) => {
+                self.mono = mono;
+                self.held_notes.clear();
+                self.patch.mono = mono;
+            },
+            SetGlide(time) => {
+                for voice in self.voices.iter_mut() {
+                    voice.glide_time = time;
+                }
+                self.patch.glide = time;
+            },
+            SetUnison(voices) => {
+                self.unison = voices.max(1).min(self.num_voices);
+                self.patch.unison = self.unison;
+            },
+            SetDetune(steps) => {
+                self.detune = steps;
+                self.patch.detune = steps;
+            },
+            SetSpread(spread) => {
+                self.spread = spread;
+                self.patch.spread = spread;
             },
             SendMidiEvent(event) => {
                 self.handle_event(event);
@@ -359,7 +1289,7 @@ impl<M> AudioDevice for SubtractiveSynth<M> where M: MidiDevice {
     }
 
     fn num_outputs(&self) -> usize {
-        1
+        2
     }
 
     fn tick(&mut self, t: Time, _: &[Sample], outputs: &mut[Sample]) {
@@ -368,24 +1298,32 @@ impl<M> AudioDevice for SubtractiveSynth<M> where M: MidiDevice {
         }
 
         self.lfo.tick(t);
-        let mut voice_out = 0.0;
-        for voice in self.voices.iter_mut() {
-            voice_out += voice.tick(t, &self.lfo.outputs);
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            let sample = voice.tick(t, &self.lfo.outputs);
+            let (left_gain, right_gain) = pan_gains(self.pan[i]);
+            left += left_gain * sample;
+            right += right_gain * sample;
         }
 
-        self.first_filter.inputs[0] = voice_out;
-        self.second_filter.inputs[0] = voice_out;
-        self.first_filter.tick(t);
-        self.second_filter.tick(t);
+        self.tremolo_l.inputs[0] = left;
+        self.tremolo_l.inputs[1] = self.lfo.outputs[0];
+        self.tremolo_l.tick(t);
+        let tremolo_left = self.tremolo_l.outputs[0];
 
-        self.tremolo.inputs[0] = match self.filter {
-            FilterType::FirstOrder => self.first_filter.outputs[0],
-            FilterType::SecondOrder => self.second_filter.outputs[0]
-        };
-        self.tremolo.inputs[1] = self.lfo.outputs[0];
-        self.tremolo.tick(t);
+        self.tremolo_r.inputs[0] = right;
+        self.tremolo_r.inputs[1] = self.lfo.outputs[0];
+        self.tremolo_r.tick(t);
+        let tremolo_right = self.tremolo_r.outputs[0];
+
+        let delayed_left = self.delay_l.tick(tremolo_left);
+        let delayed_right = self.delay_r.tick(tremolo_right);
+        let reverbed_left = self.reverb_l.tick(delayed_left);
+        let reverbed_right = self.reverb_r.tick(delayed_right);
 
-        outputs[0] = self.gain * self.tremolo.outputs[0];
+        outputs[0] = self.gain * reverbed_left;
+        outputs[1] = self.gain * reverbed_right;
     }
 }
 
@@ -397,34 +1335,158 @@ struct SubtractiveSynthVoice {
     osc1: Buffered<Oscillator>,
     osc2: Buffered<Oscillator>,
     adsr: Buffered<Adsr>,
+    fm_enabled: bool,
+    fm_depth: f32,
+    fm_ratio: f32,
+    velocity: f32,
+    velocity_sensitivity: f32,
+    velocity_to_cutoff: f32,
+    filter_lfo_depth: f32,
+    noise_level: f32,
+    noise_seed: u32,
+    filter: FilterType,
+    first_filter: Buffered<first_order::Filter>,
+    second_filter: Buffered<second_order::Filter>,
+    first_mode: first_order::FilterMode,
+    second_mode: second_order::FilterMode,
+    base_cutoff: f32,
+    filter_adsr: Buffered<Adsr>,
+    filter_env_depth: f32,
+    bend: f32,
+    pitch_adsr: Buffered<Adsr>,
+    pitch_env_depth: f32,
+    osc_mix: f32,
+    current_freq: f32,
+    target_freq: f32,
+    glide_time: f32,
+    pink_noise: bool,
+    pink_b0: f32,
+    pink_b1: f32,
+    pink_b2: f32,
+    pink_b3: f32,
+    pink_b4: f32,
+    pink_b5: f32,
+    pink_b6: f32,
 }
 
 impl SubtractiveSynthVoice {
-    /// Creates a new voice.
-    fn new() -> Self {
+    /// Creates a new voice, seeded independently from the others so their
+    /// noise oscillators decorrelate.
+    fn new(seed: u32) -> Self {
+        let first_mode = first_order::LowPass(20000.0);
+        let second_mode = second_order::LowPass(20000.0);
         SubtractiveSynthVoice {
             key_held: false,
             sustain_held: false,
             osc1: Buffered::from(Oscillator::new(oscillator::Sine)),
             osc2: Buffered::from(Oscillator::new(oscillator::Sine)),
             adsr: Buffered::from(Adsr::default(1)),
+            fm_enabled: false,
+            fm_depth: 0.0,
+            fm_ratio: 1.0,
+            velocity: 1.0,
+            velocity_sensitivity: 0.0,
+            velocity_to_cutoff: 0.0,
+            filter_lfo_depth: 0.0,
+            noise_level: 0.0,
+            noise_seed: seed,
+            filter: FilterType::FirstOrder,
+            first_filter: Buffered::from(first_order::Filter::new(first_mode, 1)),
+            second_filter: Buffered::from(second_order::Filter::new(second_mode, 1)),
+            first_mode: first_mode,
+            second_mode: second_mode,
+            base_cutoff: 20000.0,
+            filter_adsr: Buffered::from(Adsr::default(1)),
+            filter_env_depth: 0.0,
+            bend: 0.0,
+            pitch_adsr: {
+                let mut adsr = Buffered::from(Adsr::default(1));
+                adsr.handle_message(adsr::SetSustain(0.0));
+                adsr
+            },
+            pitch_env_depth: 0.0,
+            osc_mix: 0.5,
+            current_freq: 0.0,
+            target_freq: 0.0,
+            glide_time: 0.0,
+            pink_noise: false,
+            pink_b0: 0.0,
+            pink_b1: 0.0,
+            pink_b2: 0.0,
+            pink_b3: 0.0,
+            pink_b4: 0.0,
+            pink_b5: 0.0,
+            pink_b6: 0.0,
+        }
+    }
+
+    /// Generates the next white noise sample in `[-1, 1]`, using a simple
+    /// xorshift generator seeded independently per voice.
+    fn next_white(&mut self) -> Sample {
+        let mut x = self.noise_seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.noise_seed = x;
+        (x as f32 / u32::max_value() as f32) * 2.0 - 1.0
+    }
+
+    /// Generates the next noise sample in `[-1, 1]`: white noise, or pink
+    /// noise filtered from it via the Paul Kellet filter when enabled.
+    fn next_noise(&mut self) -> Sample {
+        let white = self.next_white();
+        if !self.pink_noise {
+            return white;
+        }
+
+        self.pink_b0 = 0.99886*self.pink_b0 + white*0.0555179;
+        self.pink_b1 = 0.99332*self.pink_b1 + white*0.0750759;
+        self.pink_b2 = 0.96900*self.pink_b2 + white*0.1538520;
+        self.pink_b3 = 0.86650*self.pink_b3 + white*0.3104856;
+        self.pink_b4 = 0.55000*self.pink_b4 + white*0.5329522;
+        self.pink_b5 = -0.7616*self.pink_b5 - white*0.0168980;
+        let pink = self.pink_b0 + self.pink_b1 + self.pink_b2 + self.pink_b3 +
+            self.pink_b4 + self.pink_b5 + self.pink_b6 + white*0.5362;
+        self.pink_b6 = white*0.115926;
+        pink * 0.11
+    }
+
+    /// Starts playing `freq`, shared by both the normal per-voice MIDI
+    /// handling and the synth's mono routing.
+    ///
+    /// When `legato` is true, the envelopes are left running and the pitch
+    /// glides to `freq` over [`glide_time`](#structfield.glide_time) instead
+    /// of retriggering; a `velocity` of `None` leaves the voice's current
+    /// velocity untouched, used when mono mode falls back to a held note
+    /// whose own velocity was not recorded.
+    fn note_on(&mut self, freq: f32, velocity: Option<u8>, legato: bool) {
+        self.target_freq = freq;
+        if !legato || self.glide_time <= 0.0 {
+            self.current_freq = freq;
+        }
+        if let Some(velocity) = velocity {
+            self.velocity = velocity as f32 / 127.0;
+        }
+        if !legato {
+            self.adsr.handle_message(adsr::NoteDown);
+            self.filter_adsr.handle_message(adsr::NoteDown);
+            self.pitch_adsr.handle_message(adsr::NoteDown);
         }
     }
 
     /// Handles MIDI events.
     fn handle_event(&mut self, event: MidiEvent) {
         match event.payload {
-            MidiMessage::NoteOn(note, _) => {
+            MidiMessage::NoteOn(note, velocity) => {
                 self.key_held = true;
                 let freq = midi_note_to_freq(note);
-                self.osc1.handle_message(oscillator::SetFreq(freq));
-                self.osc2.handle_message(oscillator::SetFreq(freq));
-                self.adsr.handle_message(adsr::NoteDown);
+                self.note_on(freq, Some(velocity), false);
             },
             MidiMessage::NoteOff(_, _) => {
                 self.key_held = false;
                 if !self.sustain_held {
                     self.adsr.handle_message(adsr::NoteUp);
+                    self.filter_adsr.handle_message(adsr::NoteUp);
                 }
             },
             MidiMessage::SustainPedal(true) => {
@@ -434,12 +1496,11 @@ impl SubtractiveSynthVoice {
                 self.sustain_held = false;
                 if !self.key_held {
                     self.adsr.handle_message(adsr::NoteUp);
+                    self.filter_adsr.handle_message(adsr::NoteUp);
                 }
             },
             MidiMessage::PitchBend(value) => {
-                let bend = 2.0*value;
-                self.osc1.handle_message(oscillator::SetBend(bend));
-                self.osc2.handle_message(oscillator::SetBend(bend));
+                self.bend = 2.0*value;
             },
             _ => ()
         }
@@ -448,12 +1509,330 @@ impl SubtractiveSynthVoice {
     /// Processes a single timestep, then returns the voice's output for that
     /// timestep.
     fn tick(&mut self, t: Time, lfo: &[Sample]) -> Sample {
-        self.osc1.inputs[0] = lfo[0];
+        let glide_coeff = if self.glide_time <= 0.0 {
+            1.0
+        } else {
+            1.0 - (-1.0/(self.glide_time*SAMPLE_RATE)).exp()
+        };
+        self.current_freq += (self.target_freq - self.current_freq) * glide_coeff;
+        self.osc1.handle_message(oscillator::SetFreq(self.current_freq));
+        self.osc2.handle_message(oscillator::SetFreq(
+            if self.fm_enabled { self.fm_ratio*self.current_freq } else { self.current_freq }));
+
+        self.pitch_adsr.tick(t);
+        let bend = self.bend + self.pitch_env_depth*self.pitch_adsr.outputs[0];
+        self.osc1.handle_message(oscillator::SetBend(bend));
+        self.osc2.handle_message(oscillator::SetBend(bend));
+
+        // When FM is enabled, osc2 is ticked first so its instantaneous sample
+        // can phase-modulate osc1; otherwise both oscillators just take the
+        // LFO for vibrato, as before.
         self.osc2.inputs[0] = lfo[0];
-        self.osc1.tick(t);
         self.osc2.tick(t);
-        self.adsr.inputs[0] = (self.osc1.outputs[0] + self.osc2.outputs[0]) / 2.0;
+
+        self.osc1.inputs[0] = if self.fm_enabled {
+            self.fm_depth * self.osc2.outputs[0]
+        } else {
+            lfo[0]
+        };
+        self.osc1.tick(t);
+
+        let osc_mix = if self.fm_enabled {
+            // Only the carrier reaches the envelope; the modulator is
+            // excluded from the mix.
+            self.osc1.outputs[0]
+        } else {
+            (1.0 - self.osc_mix)*self.osc1.outputs[0] + self.osc_mix*self.osc2.outputs[0]
+        };
+        let noise = self.next_noise();
+        self.adsr.inputs[0] = osc_mix + self.noise_level * noise;
         self.adsr.tick(t);
-        self.adsr.outputs[0]
+
+        self.filter_adsr.tick(t);
+        let cutoff = self.base_cutoff * 2f32.powf(
+            self.filter_env_depth * self.filter_adsr.outputs[0] +
+            self.velocity_to_cutoff * self.velocity +
+            self.filter_lfo_depth * lfo[0]);
+        let cutoff = cutoff.max(20.0).min(0.49*SAMPLE_RATE);
+
+        let filtered = match self.filter {
+            FilterType::FirstOrder => {
+                self.first_filter.handle_message(first_order::SetMode(
+                    first_order_with_cutoff(self.first_mode, cutoff)));
+                self.first_filter.inputs[0] = self.adsr.outputs[0];
+                self.first_filter.tick(t);
+                self.first_filter.outputs[0]
+            },
+            FilterType::SecondOrder => {
+                self.second_filter.handle_message(second_order::SetMode(
+                    second_order_with_cutoff(self.second_mode, cutoff)));
+                self.second_filter.inputs[0] = self.adsr.outputs[0];
+                self.second_filter.tick(t);
+                self.second_filter.outputs[0]
+            },
+        };
+
+        let gain = (1.0 - self.velocity_sensitivity) +
+            self.velocity_sensitivity * self.velocity;
+        gain * filtered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxcable::oscillator::{Saw, PolyBlep};
+
+    // A `MidiDevice` that never produces events, for driving a synth in
+    // tests without a real MIDI source.
+    struct NullMidi;
+    impl MidiDevice for NullMidi {
+        fn get_events(&mut self, _t: Time) -> Vec<MidiEvent> {
+            Vec::new()
+        }
+    }
+
+    // Builds a bowed voice with a harmonically rich waveform and the given
+    // first order lowpass cutoff, then starts a note so its filter and
+    // envelopes are active.
+    fn saw_voice(seed: u32, cutoff: f32) -> SubtractiveSynthVoice {
+        let mut voice = SubtractiveSynthVoice::new(seed);
+        voice.osc1.handle_message(oscillator::SetWaveform(Saw(PolyBlep)));
+        voice.base_cutoff = cutoff;
+        voice.first_mode = first_order::LowPass(cutoff);
+        voice.note_on(440.0, Some(100), false);
+        voice
+    }
+
+    fn run(voice: &mut SubtractiveSynthVoice, samples: usize) -> Vec<Sample> {
+        let lfo = [0.0];
+        (0 .. samples).map(|t| voice.tick(t as Time, &lfo)).collect()
+    }
+
+    #[test]
+    fn voices_filter_independently_by_cutoff() {
+        let mut bright = saw_voice(1, 18000.0);
+        let mut dark = saw_voice(2, 200.0);
+
+        let bright_out = run(&mut bright, 512);
+        let dark_out = run(&mut dark, 512);
+
+        assert_ne!(bright_out, dark_out);
+        let energy = |samples: &[Sample]| samples.iter().map(|s| s*s).sum::<f32>();
+        assert!(energy(&bright_out) > energy(&dark_out),
+            "a wide open filter should pass more energy than a dark one");
+    }
+
+    #[test]
+    fn voices_filter_independently_by_envelope_depth() {
+        fn make(depth: f32) -> SubtractiveSynthVoice {
+            let mut voice = saw_voice(3, 400.0);
+            voice.filter_env_depth = depth;
+            voice.filter_adsr.handle_message(adsr::SetAttack(0.0));
+            voice.filter_adsr.handle_message(adsr::SetDecay(0.0));
+            voice.filter_adsr.handle_message(adsr::SetSustain(1.0));
+            voice.note_on(440.0, Some(100), false);
+            voice
+        }
+
+        let mut swept = make(4.0);
+        let mut flat = make(0.0);
+
+        let swept_out = run(&mut swept, 512);
+        let flat_out = run(&mut flat, 512);
+
+        assert_ne!(swept_out, flat_out);
+        let energy = |samples: &[Sample]| samples.iter().map(|s| s*s).sum::<f32>();
+        assert!(energy(&swept_out) > energy(&flat_out),
+            "opening the filter envelope should pass more energy than a flat, zero-depth envelope");
+    }
+
+    #[test]
+    fn fm_mode_changes_the_voice_output() {
+        fn make(fm_enabled: bool) -> SubtractiveSynthVoice {
+            let mut voice = SubtractiveSynthVoice::new(11);
+            voice.osc1.handle_message(oscillator::SetWaveform(Saw(PolyBlep)));
+            voice.osc2.handle_message(oscillator::SetWaveform(Saw(PolyBlep)));
+            voice.fm_enabled = fm_enabled;
+            voice.fm_depth = 2.0;
+            voice.fm_ratio = 2.0;
+            voice.note_on(440.0, Some(100), false);
+            voice
+        }
+
+        let mut fm_on = make(true);
+        let mut fm_off = make(false);
+        assert_ne!(run(&mut fm_on, 256), run(&mut fm_off, 256));
+    }
+
+    #[test]
+    fn velocity_sensitivity_scales_output_gain() {
+        fn make(velocity: u8) -> SubtractiveSynthVoice {
+            let mut voice = SubtractiveSynthVoice::new(13);
+            voice.osc1.handle_message(oscillator::SetWaveform(Saw(PolyBlep)));
+            voice.velocity_sensitivity = 1.0;
+            voice.note_on(440.0, Some(velocity), false);
+            voice
+        }
+
+        let mut loud = make(127);
+        let mut soft = make(32);
+        let energy = |samples: &[Sample]| samples.iter().map(|s| s*s).sum::<f32>();
+        assert!(energy(&run(&mut loud, 256)) > energy(&run(&mut soft, 256)),
+            "higher velocity should produce louder output once velocity sensitivity is enabled");
+    }
+
+    #[test]
+    fn velocity_to_cutoff_brightens_harder_hits() {
+        fn make(velocity: u8) -> SubtractiveSynthVoice {
+            let mut voice = saw_voice(15, 400.0);
+            voice.velocity_to_cutoff = 4.0;
+            voice.note_on(440.0, Some(velocity), false);
+            voice
+        }
+
+        let mut hard = make(127);
+        let mut soft = make(1);
+        let energy = |samples: &[Sample]| samples.iter().map(|s| s*s).sum::<f32>();
+        assert!(energy(&run(&mut hard, 256)) > energy(&run(&mut soft, 256)),
+            "a harder hit should open the filter wider when velocity-to-cutoff tracking is enabled");
+    }
+
+    #[test]
+    fn noise_level_is_mixed_into_the_voice() {
+        fn make(level: f32) -> SubtractiveSynthVoice {
+            let mut voice = SubtractiveSynthVoice::new(17);
+            voice.noise_level = level;
+            voice.note_on(440.0, Some(100), false);
+            voice
+        }
+
+        assert_ne!(run(&mut make(1.0), 256), run(&mut make(0.0), 256));
+    }
+
+    #[test]
+    fn pink_noise_differs_from_white_noise() {
+        fn make(pink: bool) -> SubtractiveSynthVoice {
+            let mut voice = SubtractiveSynthVoice::new(19);
+            voice.noise_level = 1.0;
+            voice.pink_noise = pink;
+            voice.note_on(440.0, Some(100), false);
+            voice
+        }
+
+        assert_ne!(run(&mut make(true), 256), run(&mut make(false), 256));
+    }
+
+    #[test]
+    fn pitch_envelope_depth_changes_the_pitch_sweep() {
+        fn make(depth: f32) -> SubtractiveSynthVoice {
+            let mut voice = SubtractiveSynthVoice::new(23);
+            voice.osc1.handle_message(oscillator::SetWaveform(Saw(PolyBlep)));
+            voice.pitch_env_depth = depth;
+            voice.note_on(440.0, Some(100), false);
+            voice
+        }
+
+        assert_ne!(run(&mut make(12.0), 256), run(&mut make(0.0), 256));
+    }
+
+    #[test]
+    fn glide_time_eases_frequency_instead_of_jumping() {
+        let mut voice = SubtractiveSynthVoice::new(29);
+        voice.glide_time = 0.5;
+        voice.note_on(220.0, Some(100), false);
+        assert_eq!(voice.current_freq, 220.0);
+
+        voice.note_on(440.0, Some(100), true);
+        assert_eq!(voice.current_freq, 220.0,
+            "a legato glide shouldn't jump to the new pitch immediately");
+        assert_eq!(voice.target_freq, 440.0);
+
+        voice.tick(0 as Time, &[0.0]);
+        assert!(voice.current_freq > 220.0 && voice.current_freq < 440.0,
+            "ticking should ease current_freq toward target_freq rather than jump");
+    }
+
+    #[test]
+    fn pan_gains_spread_across_the_stereo_field() {
+        let (left_center, right_center) = pan_gains(0.0);
+        assert_eq!(left_center, right_center);
+
+        let (left_left, right_left) = pan_gains(-1.0);
+        assert!(left_left > right_left, "a hard left pan should favor the left channel");
+
+        let (left_right, right_right) = pan_gains(1.0);
+        assert!(right_right > left_right, "a hard right pan should favor the right channel");
+    }
+
+    #[test]
+    fn delay_feeds_back_the_delayed_signal() {
+        let mut delay = Delay::new();
+        delay.set_time(0.01);
+        delay.feedback = 0.5;
+        delay.mix = 1.0;
+
+        let mut echo: Vec<Sample> = vec![delay.tick(1.0)];
+        for _ in 0 .. 2000 {
+            echo.push(delay.tick(0.0));
+        }
+
+        assert!(echo.iter().skip(1).any(|&s| s.abs() > 1e-6),
+            "a positive delay time and mix should produce an echo of the impulse");
+        assert!(echo.iter().skip(1).filter(|&&s| s.abs() > 1e-6).count() > 1,
+            "positive feedback should repeat the echo more than once");
+    }
+
+    #[test]
+    fn delay_with_zero_time_is_a_dry_pass_through() {
+        let mut delay = Delay::new();
+        delay.mix = 1.0;
+        delay.feedback = 0.5;
+
+        assert_eq!(delay.tick(1.0), 1.0);
+        assert_eq!(delay.tick(0.0), 0.0);
+    }
+
+    #[test]
+    fn reverb_wet_mix_adds_a_tail_after_the_dry_signal() {
+        let mut reverb = Reverb::new();
+        reverb.wet = 1.0;
+
+        let mut tail: Vec<Sample> = vec![reverb.tick(1.0)];
+        for _ in 0 .. 2000 {
+            tail.push(reverb.tick(0.0));
+        }
+
+        assert!(tail.iter().skip(1).any(|&s| s.abs() > 1e-6),
+            "a wet reverb should leave an audible tail after the dry impulse");
+    }
+
+    #[test]
+    fn synth_patch_round_trips() {
+        let synth = SubtractiveSynth::new(NullMidi, 4)
+            .gain(-6.0)
+            .osc1(Saw(PolyBlep))
+            .fm(2.5, 3.0)
+            .noise(0.2)
+            .pink_noise(true)
+            .filter_envelope(0.01, 0.2, 0.4, 0.5, 3.0)
+            .pitch_envelope(0.01, 0.1, -2.0)
+            .osc_mix(0.3)
+            .delay(0.3, 0.4, 0.25)
+            .reverb(0.2, 0.6, 0.7)
+            .mono(true)
+            .glide(0.05)
+            .unison(3)
+            .detune(7.0)
+            .spread(0.8)
+            .velocity_sensitivity(0.5)
+            .velocity_to_cutoff(1.5)
+            .filter_lfo(2.0);
+        let patch = synth.current_patch();
+
+        let mut restored = SubtractiveSynth::new(NullMidi, 4);
+        restored.apply_patch(&patch);
+
+        assert_eq!(restored.current_patch(), patch);
     }
 }